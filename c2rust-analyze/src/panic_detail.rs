@@ -2,14 +2,18 @@ use backtrace::Backtrace;
 use log::warn;
 use std::any::Any;
 use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Write as _;
 use std::panic::{Location, PanicInfo};
+use std::sync::{Mutex, OnceLock};
 
 #[derive(Clone, Debug)]
 pub struct PanicDetail {
     msg: String,
     loc: Option<String>,
     relevant_loc: Option<String>,
+    /// The analysis phase the panic is attributed to, as classified by [`guess_relevant_loc`].
+    phase: Option<&'static str>,
     backtrace: Option<Backtrace>,
 }
 
@@ -19,6 +23,7 @@ impl PanicDetail {
             msg,
             loc: None,
             relevant_loc: None,
+            phase: None,
             backtrace: None,
         }
     }
@@ -49,10 +54,15 @@ thread_local! {
 
 pub fn panic_hook(info: &PanicInfo) {
     let bt = Backtrace::new();
+    let (phase, relevant_loc) = match guess_relevant_loc(&bt) {
+        Some((phase, loc)) => (Some(phase), Some(loc)),
+        None => (None, None),
+    };
     let detail = PanicDetail {
         msg: panic_to_string(info.payload()),
         loc: info.location().map(|l| l.to_string()),
-        relevant_loc: guess_relevant_loc(&bt),
+        relevant_loc,
+        phase,
         backtrace: Some(bt),
     };
     let old = CURRENT_PANIC_DETAIL.with(|cell| cell.replace(Some(detail)));
@@ -65,44 +75,167 @@ pub fn take_current() -> Option<PanicDetail> {
     CURRENT_PANIC_DETAIL.with(|cell| cell.take())
 }
 
+/// Catch a panic, recording it in the process-wide [`PanicReport`] under `"unknown"`. Use
+/// [`catch_with_item`] instead wherever the name of the function or static being processed is
+/// available, so the report can attribute the panic to it.
 pub fn catch(e: &(dyn Any + Send + 'static)) -> PanicDetail {
-    take_current().unwrap_or_else(|| {
+    catch_with_item(e, "unknown")
+}
+
+/// Catch a panic, recording it in the process-wide [`PanicReport`] under `item` (typically the
+/// name of the function or static being processed when the panic occurred).
+pub fn catch_with_item(e: &(dyn Any + Send + 'static), item: impl Into<String>) -> PanicDetail {
+    let detail = take_current().unwrap_or_else(|| {
         let msg = panic_to_string(e);
         warn!("missing panic detail; caught message {:?}", msg);
         PanicDetail::new(msg)
+    });
+    record(&detail, item);
+    detail
+}
+
+/// How a stack frame's symbol name maps to an analysis phase.
+#[derive(Clone, Copy)]
+enum Matcher {
+    Prefix(&'static str),
+    Contains(&'static str),
+}
+
+impl Matcher {
+    fn matches(&self, name: &str) -> bool {
+        match *self {
+            Matcher::Prefix(prefix) => name.starts_with(prefix),
+            Matcher::Contains(needle) => name.contains(needle),
+        }
+    }
+}
+
+struct PhaseRule {
+    phase: &'static str,
+    matcher: Matcher,
+}
+
+fn phase_rules() -> &'static Mutex<Vec<PhaseRule>> {
+    static RULES: OnceLock<Mutex<Vec<PhaseRule>>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        Mutex::new(vec![
+            PhaseRule {
+                phase: "dataflow",
+                matcher: Matcher::Prefix("c2rust_analyze::dataflow"),
+            },
+            PhaseRule {
+                phase: "dataflow",
+                matcher: Matcher::Contains("type_of_rvalue"),
+            },
+            PhaseRule {
+                phase: "dataflow",
+                matcher: Matcher::Contains("lty_project"),
+            },
+            PhaseRule {
+                phase: "borrowck",
+                matcher: Matcher::Prefix("c2rust_analyze::borrowck"),
+            },
+            PhaseRule {
+                phase: "rewrite",
+                matcher: Matcher::Prefix("c2rust_analyze::rewrite"),
+            },
+        ])
     })
 }
 
-fn guess_relevant_loc(bt: &Backtrace) -> Option<String> {
+/// Register a new phase classification rule, so that panics whose relevant stack frame name
+/// starts with `prefix` are attributed to `phase` in the aggregated [`PanicReport`]. Intended for
+/// analysis phases that live outside this crate's built-in set.
+pub fn register_phase_prefix(phase: &'static str, prefix: &'static str) {
+    phase_rules().lock().unwrap().push(PhaseRule {
+        phase,
+        matcher: Matcher::Prefix(prefix),
+    });
+}
+
+fn guess_relevant_loc(bt: &Backtrace) -> Option<(&'static str, String)> {
+    let rules = phase_rules().lock().unwrap();
     for frame in bt.frames() {
         for symbol in frame.symbols() {
             let name = match symbol.name() {
                 Some(x) => x.to_string(),
                 None => continue,
             };
-            if name.starts_with("c2rust_analyze::dataflow")
-                || name.starts_with("c2rust_analyze::borrowck")
-                || name.starts_with("c2rust_analyze::rewrite")
-                || name.contains("type_of_rvalue")
-                || name.contains("lty_project")
-            {
-                let filename_str = match symbol.filename() {
-                    Some(x) => x.display().to_string(),
-                    None => "[unknown]".to_string(),
-                };
-                return Some(format!(
-                    "{} @ {}:{}:{}",
-                    name,
-                    filename_str,
-                    symbol.lineno().unwrap_or(0),
-                    symbol.colno().unwrap_or(0)
-                ));
-            }
+            let rule = match rules.iter().find(|rule| rule.matcher.matches(&name)) {
+                Some(rule) => rule,
+                None => continue,
+            };
+            let filename_str = match symbol.filename() {
+                Some(x) => x.display().to_string(),
+                None => "[unknown]".to_string(),
+            };
+            let loc = format!(
+                "{} @ {}:{}:{}",
+                name,
+                filename_str,
+                symbol.lineno().unwrap_or(0),
+                symbol.colno().unwrap_or(0)
+            );
+            return Some((rule.phase, loc));
         }
     }
     None
 }
 
+/// Counts and examples of panics attributed to a single analysis phase.
+#[derive(Default)]
+struct PhaseBucket {
+    count: usize,
+    items: BTreeSet<String>,
+    example: Option<String>,
+}
+
+fn collector() -> &'static Mutex<HashMap<String, PhaseBucket>> {
+    static COLLECTOR: OnceLock<Mutex<HashMap<String, PhaseBucket>>> = OnceLock::new();
+    COLLECTOR.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Record `detail` in the process-wide panic report, attributing it to `item`. Panics with no
+/// recognized phase are grouped under `"unknown"`.
+pub fn record(detail: &PanicDetail, item: impl Into<String>) {
+    let phase = detail.phase.unwrap_or("unknown").to_string();
+    let mut collector = collector().lock().unwrap();
+    let bucket = collector.entry(phase).or_default();
+    bucket.count += 1;
+    bucket.items.insert(item.into());
+    if bucket.example.is_none() {
+        bucket.example = Some(detail.to_string_full());
+    }
+}
+
+/// A machine-readable summary of every panic recorded so far via [`record`] /
+/// [`catch_with_item`] for a single analysis phase.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct PanicReport {
+    pub phase: String,
+    pub count: usize,
+    pub examples: Vec<String>,
+    pub items: Vec<String>,
+}
+
+/// Drain the process-wide panic collector into a list of [`PanicReport`]s, sorted by descending
+/// count, so callers can triage which analysis phase fails most often and on which items.
+pub fn take_report() -> Vec<PanicReport> {
+    let mut reports: Vec<PanicReport> = collector()
+        .lock()
+        .unwrap()
+        .drain()
+        .map(|(phase, bucket)| PanicReport {
+            phase,
+            count: bucket.count,
+            examples: bucket.example.into_iter().collect(),
+            items: bucket.items.into_iter().collect(),
+        })
+        .collect();
+    reports.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.phase.cmp(&b.phase)));
+    reports
+}
+
 fn panic_to_string(e: &(dyn Any + Send + 'static)) -> String {
     match e.downcast_ref::<&'static str>() {
         Some(s) => return s.to_string(),
@@ -115,4 +248,92 @@ fn panic_to_string(e: &(dyn Any + Send + 'static)) -> String {
     }
 
     format!("unknown error: {:?}", e.type_id())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // `record`/`take_report` share process-wide statics, so tests that touch them must not run
+    // concurrently with each other (a `take_report` in one test would drain data another test is
+    // still accumulating).
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn detail(phase: &'static str, msg: &str) -> PanicDetail {
+        PanicDetail {
+            msg: msg.to_string(),
+            loc: None,
+            relevant_loc: Some(format!("{} loc", phase)),
+            phase: Some(phase),
+            backtrace: None,
+        }
+    }
+
+    #[test]
+    fn panic_report_aggregates_counts_and_items() {
+        let _guard = TEST_LOCK.lock().unwrap();
+
+        record(&detail("test_phase_agg", "boom1"), "item_a");
+        record(&detail("test_phase_agg", "boom2"), "item_b");
+        record(&detail("test_phase_agg", "boom3"), "item_a");
+
+        let reports = take_report();
+        let report = reports
+            .iter()
+            .find(|r| r.phase == "test_phase_agg")
+            .expect("phase present in report");
+        assert_eq!(report.count, 3);
+        assert_eq!(
+            report.items,
+            vec!["item_a".to_string(), "item_b".to_string()]
+        );
+        // Only the first detail's full text is kept as the representative example.
+        assert_eq!(report.examples.len(), 1);
+    }
+
+    #[test]
+    fn panic_report_sorts_by_count_desc_then_phase_asc() {
+        let _guard = TEST_LOCK.lock().unwrap();
+
+        record(&detail("test_phase_b", "x"), "i");
+        record(&detail("test_phase_a", "x"), "i");
+        record(&detail("test_phase_a", "x"), "i");
+
+        let reports = take_report();
+        let idx_a = reports
+            .iter()
+            .position(|r| r.phase == "test_phase_a")
+            .unwrap();
+        let idx_b = reports
+            .iter()
+            .position(|r| r.phase == "test_phase_b")
+            .unwrap();
+        // "test_phase_a" has the higher count (2 vs 1), so it sorts first even though
+        // "test_phase_b" would come first alphabetically.
+        assert!(idx_a < idx_b);
+    }
+
+    #[test]
+    fn take_report_drains_the_collector() {
+        let _guard = TEST_LOCK.lock().unwrap();
+
+        record(&detail("test_phase_drain", "x"), "i");
+        assert!(take_report().iter().any(|r| r.phase == "test_phase_drain"));
+        assert!(!take_report().iter().any(|r| r.phase == "test_phase_drain"));
+    }
+
+    #[test]
+    fn register_phase_prefix_adds_a_matching_rule() {
+        let _guard = TEST_LOCK.lock().unwrap();
+
+        register_phase_prefix("test_custom_phase", "test_custom_marker::");
+        let rules = phase_rules().lock().unwrap();
+        let rule = rules
+            .iter()
+            .rev()
+            .find(|rule| rule.phase == "test_custom_phase")
+            .expect("custom rule was registered");
+        assert!(rule.matcher.matches("test_custom_marker::some_fn"));
+        assert!(!rule.matcher.matches("unrelated::some_fn"));
+    }
+}
@@ -0,0 +1,344 @@
+use super::{LifetimeName, Rewrite};
+use rustc_hir::Mutability;
+use rustc_span::source_map::SourceMap;
+use rustc_span::{FileName, Span};
+use std::collections::HashMap;
+use std::fmt;
+
+pub trait Sink {
+    type Error;
+    const PARENTHESIZE_EXPRS: bool;
+
+    fn emit_str(&mut self, s: &str) -> Result<(), Self::Error>;
+    fn emit_fmt(&mut self, args: fmt::Arguments) -> Result<(), Self::Error>;
+    fn emit_expr(&mut self) -> Result<(), Self::Error>;
+    fn emit_sub(&mut self, idx: usize, span: Span) -> Result<(), Self::Error>;
+    fn emit_span(&mut self, span: Span) -> Result<(), Self::Error>;
+}
+
+/// Precedence tiers used to decide when a child rewrite needs parenthesizing. Higher binds
+/// tighter (needs fewer parens); mirrors Rust's actual operator precedence for the constructs
+/// `Rewrite` can build.
+type Prec = u8;
+const PREC_CAST: Prec = 1;
+const PREC_UNARY: Prec = 2;
+const PREC_ATOM: Prec = 3;
+
+fn prec(rw: &Rewrite) -> Prec {
+    match rw {
+        Rewrite::RemovedCast(inner) => prec(inner),
+        Rewrite::Ref(..) | Rewrite::Deref(..) => PREC_UNARY,
+        Rewrite::Cast(..) => PREC_CAST,
+        _ => PREC_ATOM,
+    }
+}
+
+pub fn emit_rewrite<S: Sink>(sink: &mut S, rw: &Rewrite) -> Result<(), S::Error> {
+    emit(sink, rw, 0)
+}
+
+/// Emit `rw`, wrapping it in parens if its precedence is lower than `min_prec` requires.
+fn emit_child<S: Sink>(sink: &mut S, rw: &Rewrite, min_prec: Prec) -> Result<(), S::Error> {
+    // We can't know the precedence of an unrewritten source expression spliced in verbatim
+    // (`Identity`/`Sub`/`Extract`), so a sink that's actually rewriting source code
+    // conservatively parenthesizes those holes whenever they're not already at the top level.
+    let needs_parens = if S::PARENTHESIZE_EXPRS && is_hole(rw) {
+        min_prec > 0
+    } else {
+        prec(rw) < min_prec
+    };
+
+    if needs_parens {
+        sink.emit_str("(")?;
+        emit(sink, rw, 0)?;
+        sink.emit_str(")")
+    } else {
+        emit(sink, rw, min_prec)
+    }
+}
+
+fn is_hole(rw: &Rewrite) -> bool {
+    matches!(
+        rw,
+        Rewrite::Identity | Rewrite::Sub(..) | Rewrite::Extract(..)
+    )
+}
+
+fn emit<S: Sink>(sink: &mut S, rw: &Rewrite, min_prec: Prec) -> Result<(), S::Error> {
+    match rw {
+        Rewrite::Identity => sink.emit_expr(),
+        Rewrite::Sub(idx, span) => sink.emit_sub(*idx, *span),
+        Rewrite::Text(s) => sink.emit_str(s),
+        Rewrite::Extract(span) => sink.emit_span(*span),
+
+        Rewrite::Ref(e, mutbl) => {
+            sink.emit_str(match mutbl {
+                Mutability::Mut => "&mut ",
+                Mutability::Not => "&",
+            })?;
+            emit_child(sink, e, PREC_UNARY)
+        }
+        Rewrite::AddrOf(e, mutbl) => {
+            sink.emit_str(match mutbl {
+                Mutability::Mut => "core::ptr::addr_of_mut!(",
+                Mutability::Not => "core::ptr::addr_of!(",
+            })?;
+            emit_child(sink, e, 0)?;
+            sink.emit_str(")")
+        }
+        Rewrite::Deref(e) => {
+            sink.emit_str("*")?;
+            emit_child(sink, e, PREC_UNARY)
+        }
+        Rewrite::Index(arr, idx) => {
+            emit_child(sink, arr, PREC_ATOM)?;
+            sink.emit_str("[")?;
+            emit_child(sink, idx, 0)?;
+            sink.emit_str("]")
+        }
+        Rewrite::SliceRange(arr, lo, hi) => {
+            emit_child(sink, arr, PREC_ATOM)?;
+            sink.emit_str("[")?;
+            if let Some(lo) = lo {
+                emit_child(sink, lo, 0)?;
+            }
+            sink.emit_str("..")?;
+            if let Some(hi) = hi {
+                emit_child(sink, hi, 0)?;
+            }
+            sink.emit_str("]")
+        }
+        Rewrite::Cast(e, ty) => {
+            emit_child(sink, e, PREC_UNARY)?;
+            sink.emit_fmt(format_args!(" as {}", ty))
+        }
+        Rewrite::RemovedCast(e) => emit(sink, e, min_prec),
+        Rewrite::LitZero => sink.emit_str("0"),
+        Rewrite::Call(name, args) => {
+            sink.emit_str(name)?;
+            sink.emit_str("(")?;
+            emit_list(sink, args)?;
+            sink.emit_str(")")
+        }
+        Rewrite::MethodCall(name, receiver, args) => {
+            emit_child(sink, receiver, PREC_ATOM)?;
+            sink.emit_fmt(format_args!(".{}(", name))?;
+            emit_list(sink, args)?;
+            sink.emit_str(")")
+        }
+        Rewrite::MapOption(receiver, param, body) => {
+            emit_child(sink, receiver, PREC_ATOM)?;
+            sink.emit_fmt(format_args!(".map(|{}| ", param))?;
+            emit_child(sink, body, 0)?;
+            sink.emit_str(")")
+        }
+        Rewrite::Block(stmts, result) => {
+            sink.emit_str("{ ")?;
+            for stmt in stmts {
+                emit_child(sink, stmt, 0)?;
+                sink.emit_str("; ")?;
+            }
+            if let Some(result) = result {
+                emit_child(sink, result, 0)?;
+            }
+            sink.emit_str(" }")
+        }
+        Rewrite::Let(bindings) => {
+            sink.emit_str("let (")?;
+            for (i, (name, _)) in bindings.iter().enumerate() {
+                if i > 0 {
+                    sink.emit_str(", ")?;
+                }
+                sink.emit_str(name)?;
+            }
+            sink.emit_str(") = (")?;
+            for (i, (_, rw)) in bindings.iter().enumerate() {
+                if i > 0 {
+                    sink.emit_str(", ")?;
+                }
+                emit_child(sink, rw, 0)?;
+            }
+            sink.emit_str(")")
+        }
+
+        Rewrite::Print(s) => sink.emit_str(s),
+        Rewrite::TyPtr(ty, mutbl) => {
+            sink.emit_str(match mutbl {
+                Mutability::Mut => "*mut ",
+                Mutability::Not => "*const ",
+            })?;
+            emit_child(sink, ty, 0)
+        }
+        Rewrite::TyRef(lt, ty, mutbl) => {
+            sink.emit_str("&")?;
+            if let LifetimeName::Explicit(name) = lt {
+                sink.emit_fmt(format_args!("{} ", name))?;
+            }
+            sink.emit_str(match mutbl {
+                Mutability::Mut => "mut ",
+                Mutability::Not => "",
+            })?;
+            emit_child(sink, ty, 0)
+        }
+        Rewrite::TySlice(ty) => {
+            sink.emit_str("[")?;
+            emit_child(sink, ty, 0)?;
+            sink.emit_str("]")
+        }
+        Rewrite::TyCtor(name, args) => {
+            sink.emit_str(name)?;
+            if !args.is_empty() {
+                sink.emit_str("<")?;
+                emit_list(sink, args)?;
+                sink.emit_str(">")?;
+            }
+            Ok(())
+        }
+        Rewrite::_TyGenericParams(params) => {
+            sink.emit_str("<")?;
+            emit_list(sink, params)?;
+            sink.emit_str(">")
+        }
+        Rewrite::StaticMut(mutbl, span) => {
+            sink.emit_str(match mutbl {
+                Mutability::Mut => "static mut ",
+                Mutability::Not => "static ",
+            })?;
+            sink.emit_span(*span)
+        }
+        Rewrite::DefineFn {
+            name,
+            arg_tys,
+            return_ty,
+            body,
+        } => {
+            sink.emit_fmt(format_args!("fn {}(", name))?;
+            for (i, ty) in arg_tys.iter().enumerate() {
+                if i > 0 {
+                    sink.emit_str(", ")?;
+                }
+                sink.emit_fmt(format_args!("arg{}: ", i))?;
+                emit_child(sink, ty, 0)?;
+            }
+            sink.emit_str(")")?;
+            if let Some(return_ty) = return_ty {
+                sink.emit_str(" -> ")?;
+                emit_child(sink, return_ty, 0)?;
+            }
+            sink.emit_str(" { ")?;
+            emit_child(sink, body, 0)?;
+            sink.emit_str(" }")
+        }
+        Rewrite::FnArg(idx) => sink.emit_fmt(format_args!("arg{}", idx)),
+    }
+}
+
+fn emit_list<S: Sink>(sink: &mut S, items: &[Rewrite]) -> Result<(), S::Error> {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            sink.emit_str(", ")?;
+        }
+        emit_child(sink, item, 0)?;
+    }
+    Ok(())
+}
+
+/// A [`Sink`] that splices rewrites into the real source text of the file(s) they came from,
+/// pulling `Identity`/`Sub`/`Extract` holes out of the original source via `SourceMap`.
+struct StringSink<'sm> {
+    source_map: &'sm SourceMap,
+    /// The span of the expression currently being rewritten; this is what `Identity` refers to.
+    current_span: Span,
+    out: String,
+}
+
+impl Sink for StringSink<'_> {
+    type Error = fmt::Error;
+    // Source text spliced in from `Identity`/`Sub`/`Extract` has unknown precedence, so always
+    // parenthesize it defensively unless it's already at the top level of the rewrite.
+    const PARENTHESIZE_EXPRS: bool = true;
+
+    fn emit_str(&mut self, s: &str) -> fmt::Result {
+        self.out.push_str(s);
+        Ok(())
+    }
+    fn emit_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        fmt::Write::write_fmt(&mut self.out, args)
+    }
+    fn emit_expr(&mut self) -> fmt::Result {
+        self.emit_span(self.current_span)
+    }
+    fn emit_sub(&mut self, _idx: usize, span: Span) -> fmt::Result {
+        self.emit_span(span)
+    }
+    fn emit_span(&mut self, span: Span) -> fmt::Result {
+        match self.source_map.span_to_snippet(span) {
+            Ok(snippet) => self.out.push_str(&snippet),
+            // The span doesn't map to real source (e.g. it came from macro expansion); emit a
+            // clearly-marked placeholder rather than silently dropping text.
+            Err(_) => self.out.push_str("/* <unprintable span> */"),
+        }
+        Ok(())
+    }
+}
+
+/// Apply `rewrites` to the source of each file they reference, returning the new contents of
+/// every file that has at least one rewrite.
+pub fn apply_rewrites(
+    source_map: &SourceMap,
+    mut rewrites: Vec<(Span, Rewrite)>,
+) -> Vec<(FileName, String)> {
+    rewrites.sort_by_key(|&(span, _)| (span.lo(), span.hi()));
+
+    let mut by_file: HashMap<FileName, Vec<(Span, Rewrite)>> = HashMap::new();
+    for (span, rw) in rewrites {
+        let filename = source_map.span_to_filename(span);
+        by_file.entry(filename).or_default().push((span, rw));
+    }
+
+    let mut out = Vec::with_capacity(by_file.len());
+    for (filename, file_rewrites) in by_file {
+        let source_file = match source_map.get_source_file(&filename) {
+            Some(sf) => sf,
+            None => continue,
+        };
+        let old_src = match source_file.src.as_ref() {
+            Some(src) => src,
+            None => continue,
+        };
+
+        let mut new_src = String::with_capacity(old_src.len());
+        let mut pos = source_file.start_pos;
+        for (span, rw) in &file_rewrites {
+            if span.lo() < pos {
+                // Overlapping rewrite; skip it rather than corrupt already-emitted text.
+                continue;
+            }
+            let gap = Span::new(pos, span.lo(), span.ctxt(), None);
+            if let Ok(snippet) = source_map.span_to_snippet(gap) {
+                new_src.push_str(&snippet);
+            }
+
+            let mut sink = StringSink {
+                source_map,
+                current_span: *span,
+                out: String::new(),
+            };
+            let _ = emit_rewrite(&mut sink, rw);
+            new_src.push_str(&sink.out);
+            pos = span.hi();
+        }
+        let tail = Span::new(
+            pos,
+            source_file.end_pos,
+            rustc_span::SyntaxContext::root(),
+            None,
+        );
+        if let Ok(snippet) = source_map.span_to_snippet(tail) {
+            new_src.push_str(&snippet);
+        }
+
+        out.push((filename, new_src));
+    }
+    out
+}
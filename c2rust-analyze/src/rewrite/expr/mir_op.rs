@@ -0,0 +1,366 @@
+//! Abstract, MIR-level rewrites. `gen_mir_rewrites` annotates MIR locations with the desired
+//! changes, using both the static analysis results and (optionally) a runtime
+//! [`PointerDerivationGraph`]; `rewrite::expr::convert` (via [`convert_mir_rewrite`] here) then
+//! lowers each [`RewriteKind`] into a concrete [`Rewrite`] once it's been matched up with its HIR
+//! expression.
+
+use super::{PointerDerivationGraph, PointerObservation};
+use crate::context::{AnalysisCtxt, Assignment};
+use crate::pointee_type::PointeeTypes;
+use crate::pointer_id::PointerTable;
+use crate::rewrite::Rewrite;
+use crate::type_desc::{PtrKind, TypeDesc};
+use rustc_hir::Mutability;
+use rustc_middle::mir::{Body, Location};
+
+/// The closure parameter name used for every `Rewrite::MapOption` built in this module. Nested
+/// `MapOption`s shadow this in the usual Rust way, which is harmless since each closure only ever
+/// refers to its own immediately-bound parameter.
+const MAP_OPTION_PARAM: &str = "__c2rust_x";
+
+/// One MIR-level rewrite: apply `kind` at the expression(s) lowered from `loc`.
+#[derive(Clone, Debug)]
+pub struct MirRewrite {
+    pub loc: Location,
+    pub kind: RewriteKind,
+}
+
+/// Abstract, MIR-level description of a rewrite, independent of the concrete HIR expression it
+/// will eventually apply to.
+#[derive(Clone, Debug)]
+pub enum RewriteKind {
+    /// Replace a null pointer literal (`0 as *mut T`/`0 as *const T`) with `None`.
+    NullToNone,
+    /// Dereference a pointer that may be null. `reborrow` selects `.as_deref()`/`.as_mut()` when
+    /// a reborrow (rather than a move out of the `Option`) is required; `None` means `.unwrap()`.
+    DerefOption { reborrow: Option<Mutability> },
+    /// Narrow a pointer to a sub-slice. When `option` is set the receiver is itself an `Option`,
+    /// so the slicing must happen inside a `.map(..)` rather than directly.
+    OffsetSlice {
+        option: bool,
+        start: Option<usize>,
+        end: Option<usize>,
+    },
+    /// Cast between two pointer-like types. `from` and `to` must agree on nullability; the cast
+    /// is a no-op if the two descriptors are otherwise equal.
+    Cast { from: TypeDesc, to: TypeDesc },
+}
+
+/// Builds the `Rewrite` for a pointer-to-pointer cast, enforcing that the two `TypeDesc`s agree
+/// on nullability (`from.option == to.option`) before comparing them for equality. Preserving
+/// this invariant at every cast-insertion point is what lets the `from == to` check below work.
+pub struct CastBuilder {
+    from: TypeDesc,
+    to: TypeDesc,
+}
+
+impl CastBuilder {
+    pub fn new(from: TypeDesc, to: TypeDesc) -> Self {
+        assert_eq!(
+            from.option, to.option,
+            "cast endpoints must agree on nullability: {:?} -> {:?}",
+            from, to
+        );
+        CastBuilder { from, to }
+    }
+
+    /// Build the rewrite for casting `e` from `self.from` to `self.to`. Returns `e` unchanged
+    /// when the two descriptors are otherwise equal, since no source change is needed. When both
+    /// sides are nullable, the conversion is threaded through a `.map(..)` onto the inner
+    /// (non-`Option`) target type instead of unwrapping first.
+    pub fn build(&self, e: Rewrite) -> Rewrite {
+        if self.from == self.to {
+            return e;
+        }
+        if self.to.option {
+            let body = self.convert_to_base(Rewrite::Text(MAP_OPTION_PARAM.to_owned()));
+            return Rewrite::MapOption(Box::new(e), MAP_OPTION_PARAM.to_owned(), Box::new(body));
+        }
+        self.convert_to_base(e)
+    }
+
+    /// Convert `e` into `self.to`'s target type, ignoring any `Option` wrapper (the caller
+    /// handles threading that through separately). `as` can only convert between raw pointers,
+    /// so `Ref` and `Box` targets need their own construct rather than a `Cast`: `&*e`/`&mut *e`
+    /// for a reference, `Box::from_raw(e)` for a `Box`.
+    fn convert_to_base(&self, e: Rewrite) -> Rewrite {
+        match &self.to.ptr {
+            PtrKind::Raw(_) => Rewrite::Cast(Box::new(e), self.to.base_rewrite().to_string()),
+            PtrKind::Ref(_, mutbl) => Rewrite::Ref(Box::new(Rewrite::Deref(Box::new(e))), *mutbl),
+            PtrKind::Box => Rewrite::Call("Box::from_raw".to_owned(), vec![e]),
+        }
+    }
+}
+
+/// Lower a single MIR-level [`RewriteKind`] into a concrete HIR [`Rewrite`], given the rewrite's
+/// operand expression `e`.
+pub fn convert_mir_rewrite(kind: &RewriteKind, e: Rewrite) -> Rewrite {
+    match kind {
+        RewriteKind::NullToNone => Rewrite::Text("None".to_owned()),
+        RewriteKind::DerefOption { reborrow } => match reborrow {
+            Some(Mutability::Mut) => Rewrite::MethodCall("as_mut".to_owned(), Box::new(e), vec![]),
+            Some(Mutability::Not) => {
+                Rewrite::MethodCall("as_deref".to_owned(), Box::new(e), vec![])
+            }
+            None => Rewrite::MethodCall("unwrap".to_owned(), Box::new(e), vec![]),
+        },
+        RewriteKind::OffsetSlice { option, start, end } => {
+            let start = start.map(|n| Box::new(int_lit(n)));
+            let end = end.map(|n| Box::new(int_lit(n)));
+            if *option {
+                // `p.map(|p| &p[start..end])` rather than `&p[start..end]`, so the `Option` is
+                // threaded through instead of being unwrapped. The slice is taken of the
+                // closure's own parameter, not the receiver `e`, hence `Text` rather than
+                // `Identity` (which would re-splice `e`'s span instead).
+                let body = Rewrite::SliceRange(
+                    Box::new(Rewrite::Text(MAP_OPTION_PARAM.to_owned())),
+                    start,
+                    end,
+                );
+                Rewrite::MapOption(Box::new(e), MAP_OPTION_PARAM.to_owned(), Box::new(body))
+            } else {
+                Rewrite::SliceRange(Box::new(e), start, end)
+            }
+        }
+        RewriteKind::Cast { from, to } => CastBuilder::new(from.clone(), to.clone()).build(e),
+    }
+}
+
+fn int_lit(n: usize) -> Rewrite {
+    if n == 0 {
+        Rewrite::LitZero
+    } else {
+        Rewrite::Text(n.to_string())
+    }
+}
+
+/// Generate the MIR-level rewrites for `mir`, merging the static slice requirement from
+/// [`pointer_must_be_slice`] with an optional runtime [`PointerDerivationGraph`]. A static
+/// conclusion that a pointer must be a slice always wins over a missing runtime observation, and
+/// an observation then sets that slice's upper bound to the observed element count. Where no
+/// slice is statically required, a pointer observed at a nonzero offset becomes a slice bounded
+/// the same way; one observed only at offset 0, or not observed at all, is not rewritten.
+pub fn gen_mir_rewrites<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    asn: &Assignment,
+    pointee_types: PointerTable<PointeeTypes<'tcx>>,
+    mir: &Body<'tcx>,
+    pointer_derivation_graph: Option<&PointerDerivationGraph>,
+) -> Vec<MirRewrite> {
+    let mut out = Vec::new();
+    for (block_id, block) in mir.basic_blocks.iter_enumerated() {
+        for stmt_idx in 0..block.statements.len() {
+            let loc = Location {
+                block: block_id,
+                statement_index: stmt_idx,
+            };
+
+            let must_be_slice = pointer_must_be_slice(acx, asn, &pointee_types, loc);
+            let observation = pointer_derivation_graph.and_then(|g| g.observation_at(loc));
+
+            if let Some(kind) = merge_slice_rewrite_kind(must_be_slice, observation) {
+                out.push(MirRewrite { loc, kind });
+            }
+        }
+    }
+    out
+}
+
+/// The pure decision table behind [`gen_mir_rewrites`]'s merge of static and runtime results:
+/// whether, and how, to rewrite a pointer as a slice given whether the static analysis requires
+/// a slice and what (if anything) the runtime profile observed for it.
+fn merge_slice_rewrite_kind(
+    must_be_slice: bool,
+    observation: Option<PointerObservation>,
+) -> Option<RewriteKind> {
+    match (must_be_slice, observation) {
+        // The static analysis requires a slice; tighten its bound from the observed
+        // element count when we have one, but never drop the slice itself.
+        (true, Some(PointerObservation { count, .. })) => Some(RewriteKind::OffsetSlice {
+            option: false,
+            start: None,
+            end: Some(count),
+        }),
+        (true, None) => Some(RewriteKind::OffsetSlice {
+            option: false,
+            start: None,
+            end: None,
+        }),
+        // No static requirement, and the pointer is only ever dereferenced at offset 0:
+        // prefer a single reference over a spurious `[..]` slice.
+        (false, Some(PointerObservation { max_offset: 0, .. })) => None,
+        (false, Some(PointerObservation { count, .. })) => Some(RewriteKind::OffsetSlice {
+            option: false,
+            start: None,
+            end: Some(count),
+        }),
+        (false, None) => None,
+    }
+}
+
+/// Whether the static analysis alone (ignoring any runtime profile) requires this pointer to be
+/// rewritten as a slice. The full analysis lives in the pointee/assignment passes that aren't
+/// part of this module, and they are not consulted here: this always returns `false`, so every
+/// slice rewrite from [`gen_mir_rewrites`] comes from a runtime observation.
+fn pointer_must_be_slice<'tcx>(
+    _acx: &AnalysisCtxt<'_, 'tcx>,
+    _asn: &Assignment,
+    _pointee_types: &PointerTable<PointeeTypes<'tcx>>,
+    _loc: Location,
+) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rewrite::LifetimeName;
+
+    fn ref_desc(mutbl: Mutability, option: bool) -> TypeDesc {
+        TypeDesc {
+            ptr: PtrKind::Ref(LifetimeName::Elided, mutbl),
+            pointee: Box::new(Rewrite::Print("S".to_owned())),
+            option,
+        }
+    }
+
+    #[test]
+    fn merge_slice_rewrite_kind_static_slice_without_observation_is_unbounded() {
+        assert!(matches!(
+            merge_slice_rewrite_kind(true, None),
+            Some(RewriteKind::OffsetSlice {
+                option: false,
+                start: None,
+                end: None
+            })
+        ));
+    }
+
+    #[test]
+    fn merge_slice_rewrite_kind_static_slice_with_observation_tightens_bound() {
+        let obs = PointerObservation {
+            max_offset: 3,
+            count: 4,
+        };
+        assert!(matches!(
+            merge_slice_rewrite_kind(true, Some(obs)),
+            Some(RewriteKind::OffsetSlice {
+                option: false,
+                start: None,
+                end: Some(4)
+            })
+        ));
+    }
+
+    #[test]
+    fn merge_slice_rewrite_kind_zero_offset_prefers_single_ref_over_slice() {
+        let obs = PointerObservation {
+            max_offset: 0,
+            count: 1,
+        };
+        assert!(merge_slice_rewrite_kind(false, Some(obs)).is_none());
+    }
+
+    #[test]
+    fn merge_slice_rewrite_kind_nonzero_offset_emits_bounded_slice() {
+        let obs = PointerObservation {
+            max_offset: 5,
+            count: 6,
+        };
+        assert!(matches!(
+            merge_slice_rewrite_kind(false, Some(obs)),
+            Some(RewriteKind::OffsetSlice {
+                option: false,
+                start: None,
+                end: Some(6)
+            })
+        ));
+    }
+
+    #[test]
+    fn merge_slice_rewrite_kind_no_static_requirement_and_no_observation_is_unchanged() {
+        assert!(merge_slice_rewrite_kind(false, None).is_none());
+    }
+
+    #[test]
+    fn cast_builder_is_noop_for_equal_descs() {
+        let d = ref_desc(Mutability::Not, true);
+        let rw = CastBuilder::new(d.clone(), d).build(Rewrite::Text("e".to_owned()));
+        assert_eq!(rw.to_string(), "e");
+    }
+
+    #[test]
+    fn cast_builder_nullable_cast_threads_option_through_map() {
+        // Both sides are nullable but the inner type changes, so the reborrow must go inside a
+        // `.map(..)` rather than unwrapping the `Option` first.
+        let from = ref_desc(Mutability::Not, true);
+        let to = ref_desc(Mutability::Mut, true);
+        let rw = CastBuilder::new(from, to).build(Rewrite::Text("e".to_owned()));
+        assert_eq!(rw.to_string(), "e.map(|__c2rust_x| &mut *__c2rust_x)");
+    }
+
+    #[test]
+    fn cast_builder_raw_to_raw_uses_as_cast() {
+        let from = TypeDesc {
+            ptr: PtrKind::Raw(Mutability::Not),
+            pointee: Box::new(Rewrite::Print("S".to_owned())),
+            option: false,
+        };
+        let to = TypeDesc {
+            ptr: PtrKind::Raw(Mutability::Mut),
+            pointee: Box::new(Rewrite::Print("S".to_owned())),
+            option: false,
+        };
+        let rw = CastBuilder::new(from, to).build(Rewrite::Text("e".to_owned()));
+        assert_eq!(rw.to_string(), "e as *mut S");
+    }
+
+    #[test]
+    fn cast_builder_raw_to_box_uses_from_raw() {
+        let from = TypeDesc {
+            ptr: PtrKind::Raw(Mutability::Mut),
+            pointee: Box::new(Rewrite::Print("S".to_owned())),
+            option: false,
+        };
+        let to = TypeDesc {
+            ptr: PtrKind::Box,
+            pointee: Box::new(Rewrite::Print("S".to_owned())),
+            option: false,
+        };
+        let rw = CastBuilder::new(from, to).build(Rewrite::Text("e".to_owned()));
+        assert_eq!(rw.to_string(), "Box::from_raw(e)");
+    }
+
+    #[test]
+    fn cast_builder_raw_to_ref_reborrows_instead_of_casting() {
+        let from = TypeDesc {
+            ptr: PtrKind::Raw(Mutability::Not),
+            pointee: Box::new(Rewrite::Print("S".to_owned())),
+            option: false,
+        };
+        let to = ref_desc(Mutability::Not, false);
+        let rw = CastBuilder::new(from, to).build(Rewrite::Text("e".to_owned()));
+        assert_eq!(rw.to_string(), "&*e");
+    }
+
+    #[test]
+    #[should_panic(expected = "cast endpoints must agree on nullability")]
+    fn cast_builder_rejects_mismatched_nullability() {
+        let from = ref_desc(Mutability::Not, true);
+        let to = ref_desc(Mutability::Not, false);
+        CastBuilder::new(from, to);
+    }
+
+    #[test]
+    fn convert_offset_slice_option_uses_a_real_closure_param_not_the_receiver() {
+        let rw = convert_mir_rewrite(
+            &RewriteKind::OffsetSlice {
+                option: true,
+                start: None,
+                end: Some(2),
+            },
+            Rewrite::Text("p".to_owned()),
+        );
+        assert_eq!(rw.to_string(), "p.map(|__c2rust_x| __c2rust_x[..2])");
+    }
+}
@@ -3,8 +3,9 @@ use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::PointerTable;
 use crate::rewrite::Rewrite;
 use rustc_hir::BodyId;
-use rustc_middle::mir::Body;
+use rustc_middle::mir::{Body, Location};
 use rustc_span::Span;
+use std::collections::HashMap;
 
 mod convert;
 mod distribute;
@@ -15,14 +16,44 @@ mod unlower;
 pub use self::convert::convert_cast_rewrite;
 pub use self::mir_op::CastBuilder;
 
+/// A single allocation site's observed extent, as recorded by a runtime pointer-derivation
+/// profile: the farthest offset and the number of distinct elements ever reached through a
+/// pointer derived from that site.
+#[derive(Clone, Copy, Debug)]
+pub struct PointerObservation {
+    pub max_offset: usize,
+    pub count: usize,
+}
+
+/// An optional runtime profile of how pointers were actually used, keyed by the MIR location of
+/// the allocation/def site each pointer was derived from. When present, this is merged with the
+/// static analysis results to tighten slice bounds and prefer single references over slices for
+/// pointers that are never offset; static "must be a slice" conclusions always take precedence,
+/// so a missing observation never downgrades a slice to a single reference.
+#[derive(Clone, Debug, Default)]
+pub struct PointerDerivationGraph {
+    observations: HashMap<Location, PointerObservation>,
+}
+
+impl PointerDerivationGraph {
+    pub fn new(observations: HashMap<Location, PointerObservation>) -> Self {
+        Self { observations }
+    }
+
+    pub fn observation_at(&self, site: Location) -> Option<PointerObservation> {
+        self.observations.get(&site).copied()
+    }
+}
+
 pub fn gen_expr_rewrites<'tcx>(
     acx: &AnalysisCtxt<'_, 'tcx>,
     asn: &Assignment,
     pointee_types: PointerTable<PointeeTypes<'tcx>>,
     mir: &Body<'tcx>,
     hir_body_id: BodyId,
+    pointer_derivation_graph: Option<&PointerDerivationGraph>,
 ) -> Vec<(Span, Rewrite)> {
-    let mir_rewrites = mir_op::gen_mir_rewrites(acx, asn, pointee_types, mir);
+    let mir_rewrites = mir_op::gen_mir_rewrites(acx, asn, pointee_types, mir, pointer_derivation_graph);
     let unlower_map = unlower::unlower(acx.tcx(), mir, hir_body_id);
     let rewrites_by_expr = distribute::distribute(acx.tcx(), unlower_map, mir_rewrites);
     let hir_rewrites = convert::convert_rewrites(acx.tcx(), hir_body_id, rewrites_by_expr);
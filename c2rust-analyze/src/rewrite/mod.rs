@@ -23,12 +23,16 @@
 //! require us to update the `Span`s mentioned in the later rewrites to account for the changes in
 //! the source code produced by the earlier ones).
 
+use log::warn;
 use rustc_hir::Mutability;
 use rustc_middle::mir::Body;
 use rustc_middle::mir::Location;
 use rustc_middle::ty::TyCtxt;
-use rustc_span::Span;
+use rustc_span::{FileName, Span};
 use std::fmt;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
 
 mod apply;
 mod expr;
@@ -88,6 +92,11 @@ pub enum Rewrite<S = Span> {
     /// A block of statements, followed by an optional result expression.  This rewrite inserts a
     /// semicolon after each statement.
     Block(Vec<Rewrite>, Option<Box<Rewrite>>),
+    /// `receiver.map(|param| body)`.  Used to thread a rewrite through an `Option<T>` without
+    /// unwrapping it, e.g. turning `&p[start..]` into `p.map(|p| &p[start..])` when `p` is a
+    /// nullable pointer.  `body` refers to the closure argument via `Rewrite::Text(param)`, since
+    /// `Identity` would instead re-splice the receiver's own span.
+    MapOption(Box<Rewrite>, String, Box<Rewrite>),
     /// A multi-variable `let` binding, like `let (x, y) = (rw0, rw1)`.  Note that this rewrite
     /// does not include a trailing semicolon.
     ///
@@ -157,24 +166,264 @@ impl apply::Sink for FormatterSink<'_, '_> {
     }
 }
 
-pub fn apply_rewrites(tcx: TyCtxt, rewrites: Vec<(Span, Rewrite)>) {
-    // TODO: emit new source code properly instead of just printing
-    let new_src = apply::apply_rewrites(tcx.sess.source_map(), rewrites);
+/// How `apply_rewrites` should emit the rewritten source for each file.
+#[derive(Clone, Copy, Debug)]
+pub enum OutputMode {
+    /// Print each rewritten file to stderr.  This is the original debug-only behavior.
+    Print,
+    /// Overwrite each file in place with its rewritten contents.  If `backup` is set, the
+    /// original contents are first saved alongside it with a `.orig` extension.
+    WriteInPlace { backup: bool },
+    /// Produce a unified diff of the old and new source for each file, using `context_lines`
+    /// lines of context around each change instead of writing or printing the new source.
+    Diff { context_lines: usize },
+}
 
-    for (filename, src) in new_src {
-        eprintln!("\n\n ===== BEGIN {:?} =====", filename);
-        for line in src.lines() {
-            // Omit filecheck directives from the debug output, as filecheck can get confused due
-            // to directives matching themselves (e.g. `// CHECK: foo` will match the `foo` in the
-            // line `// CHECK: foo`).
-            if let Some((pre, _post)) = line.split_once("// CHECK") {
-                eprintln!("{}// (FileCheck directive omitted)", pre);
-            } else {
-                eprintln!("{}", line);
+/// The outcome of applying rewrites to a single file, as reported by [`apply_rewrites`].
+#[derive(Clone, Debug)]
+pub struct FileRewriteResult {
+    pub filename: FileName,
+    /// Whether the rewritten source differs from the original.
+    pub changed: bool,
+    /// The unified diff text, if `OutputMode::Diff` was used and the file changed.
+    pub diff: Option<String>,
+}
+
+pub fn apply_rewrites(
+    tcx: TyCtxt,
+    rewrites: Vec<(Span, Rewrite)>,
+    mode: OutputMode,
+) -> Vec<FileRewriteResult> {
+    let source_map = tcx.sess.source_map();
+    let new_src = apply::apply_rewrites(source_map, rewrites);
+
+    let mut results = Vec::with_capacity(new_src.len());
+    for (filename, new_text) in new_src {
+        let old_text = old_source_text(tcx, &filename);
+        let changed = old_text.as_deref() != Some(new_text.as_str());
+
+        let diff = match mode {
+            OutputMode::Print => {
+                print_rewritten_file(&filename, &new_text);
+                None
+            }
+            OutputMode::WriteInPlace { backup } => {
+                write_in_place(&filename, old_text.as_deref(), &new_text, backup);
+                None
+            }
+            OutputMode::Diff { context_lines } if changed => old_text
+                .as_deref()
+                .map(|old_text| unified_diff(&filename, old_text, &new_text, context_lines)),
+            OutputMode::Diff { .. } => None,
+        };
+
+        results.push(FileRewriteResult {
+            filename,
+            changed,
+            diff,
+        });
+    }
+    results
+}
+
+/// Look up the pre-rewrite source text of `filename`, if the compiler still has it loaded.
+fn old_source_text(tcx: TyCtxt, filename: &FileName) -> Option<String> {
+    let source_file = tcx.sess.source_map().get_source_file(filename)?;
+    source_file.src.as_ref().map(|src| src.to_string())
+}
+
+fn print_rewritten_file(filename: &FileName, src: &str) {
+    eprintln!("\n\n ===== BEGIN {:?} =====", filename);
+    for line in src.lines() {
+        // Omit filecheck directives from the debug output, as filecheck can get confused due
+        // to directives matching themselves (e.g. `// CHECK: foo` will match the `foo` in the
+        // line `// CHECK: foo`).
+        if let Some((pre, _post)) = line.split_once("// CHECK") {
+            eprintln!("{}// (FileCheck directive omitted)", pre);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+    eprintln!(" ===== END {:?} =====", filename);
+}
+
+fn write_in_place(filename: &FileName, old_text: Option<&str>, new_text: &str, backup: bool) {
+    let path = match filename {
+        FileName::Real(real) => real.local_path(),
+        _ => {
+            warn!("can't write rewritten source for non-local file {:?}", filename);
+            return;
+        }
+    };
+    let path = match path {
+        Some(path) => path,
+        None => {
+            warn!("can't write rewritten source for non-local file {:?}", filename);
+            return;
+        }
+    };
+
+    if backup {
+        if let Some(old_text) = old_text {
+            if let Err(e) = fs::write(path.with_extension(backup_extension(path)), old_text) {
+                warn!("failed to write backup for {:?}: {}", path, e);
             }
         }
-        eprintln!(" ===== END {:?} =====", filename);
     }
+
+    if let Err(e) = fs::write(path, new_text) {
+        warn!("failed to write rewritten source for {:?}: {}", path, e);
+    }
+}
+
+fn backup_extension(path: &Path) -> std::ffi::OsString {
+    let mut ext = path.extension().map(|e| e.to_owned()).unwrap_or_default();
+    if !ext.is_empty() {
+        ext.push(".");
+    }
+    ext.push("orig");
+    ext
+}
+
+/// Build a unified diff of `old_text` vs. `new_text`, with `context_lines` lines of context
+/// around each changed region.  Callers should only invoke this when the two texts are known to
+/// differ; given identical input it returns only the `---`/`+++` header lines, with no hunks.
+fn unified_diff(filename: &FileName, old_text: &str, new_text: &str, context_lines: usize) -> String {
+    let old_lines: Vec<&str> = old_text.lines().collect();
+    let new_lines: Vec<&str> = new_text.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let mut out = String::new();
+    writeln!(out, "--- {:?}", filename).unwrap();
+    writeln!(out, "+++ {:?}", filename).unwrap();
+
+    let mut i = 0;
+    while i < ops.len() {
+        if matches!(ops[i], DiffOp::Equal(_, _)) {
+            i += 1;
+            continue;
+        }
+
+        // Find the end of this run of changes, including any short equal runs that fall
+        // within `context_lines` of the next change.
+        let mut j = i;
+        while j < ops.len() {
+            match ops[j] {
+                DiffOp::Equal(_, _) => {
+                    let mut k = j;
+                    while k < ops.len() && matches!(ops[k], DiffOp::Equal(_, _)) {
+                        k += 1;
+                    }
+                    let gap = k - j;
+                    if k >= ops.len() || gap > context_lines * 2 {
+                        break;
+                    }
+                    j = k;
+                }
+                _ => j += 1,
+            }
+        }
+
+        let ctx_start = i.saturating_sub(context_lines);
+        let ctx_end = (j + context_lines).min(ops.len());
+
+        let (old_start, new_start) = hunk_start(&ops, ctx_start);
+        let mut old_count = 0;
+        let mut new_count = 0;
+        let mut body = String::new();
+        for op in &ops[ctx_start..ctx_end] {
+            match op {
+                DiffOp::Equal(o, n) => {
+                    writeln!(body, " {}", old_lines.get(*o).or_else(|| new_lines.get(*n)).unwrap()).unwrap();
+                    old_count += 1;
+                    new_count += 1;
+                }
+                DiffOp::Delete(o) => {
+                    writeln!(body, "-{}", old_lines[*o]).unwrap();
+                    old_count += 1;
+                }
+                DiffOp::Insert(n) => {
+                    writeln!(body, "+{}", new_lines[*n]).unwrap();
+                    new_count += 1;
+                }
+            }
+        }
+
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_count,
+            new_start + 1,
+            new_count
+        )
+        .unwrap();
+        out.push_str(&body);
+
+        i = ctx_end;
+    }
+
+    out
+}
+
+#[derive(Clone, Copy, Debug)]
+enum DiffOp {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+fn hunk_start(ops: &[DiffOp], idx: usize) -> (usize, usize) {
+    for op in &ops[idx..] {
+        match op {
+            DiffOp::Equal(o, n) => return (*o, *n),
+            DiffOp::Delete(o) => return (*o, *o),
+            DiffOp::Insert(n) => return (*n, *n),
+        }
+    }
+    (0, 0)
+}
+
+/// A minimal line-oriented diff based on longest-common-subsequence.  Sized for the kind of
+/// single-function rewrites this module produces, not for diffing huge files.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<DiffOp> {
+    let n = old.len();
+    let m = new.len();
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            ops.push(DiffOp::Equal(i, j));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(DiffOp::Delete(i));
+            i += 1;
+        } else {
+            ops.push(DiffOp::Insert(j));
+            j += 1;
+        }
+    }
+    while i < n {
+        ops.push(DiffOp::Delete(i));
+        i += 1;
+    }
+    while j < m {
+        ops.push(DiffOp::Insert(j));
+        j += 1;
+    }
+    ops
 }
 
 #[cfg(test)]
@@ -230,4 +479,89 @@ mod test {
             "$e[$e][$e]",
         );
     }
+
+    fn lines(s: &[&str]) -> Vec<&str> {
+        s.to_vec()
+    }
+
+    #[test]
+    fn diff_lines_all_equal() {
+        let a = lines(&["a", "b", "c"]);
+        let ops = diff_lines(&a, &a);
+        assert!(ops
+            .iter()
+            .all(|op| matches!(op, DiffOp::Equal(i, j) if i == j)));
+        assert_eq!(ops.len(), 3);
+    }
+
+    #[test]
+    fn diff_lines_pure_insert() {
+        let old = lines(&["a", "c"]);
+        let new = lines(&["a", "b", "c"]);
+        let ops = diff_lines(&old, &new);
+        assert!(matches!(ops[0], DiffOp::Equal(0, 0)));
+        assert!(matches!(ops[1], DiffOp::Insert(1)));
+        assert!(matches!(ops[2], DiffOp::Equal(1, 2)));
+    }
+
+    #[test]
+    fn diff_lines_pure_delete() {
+        let old = lines(&["a", "b", "c"]);
+        let new = lines(&["a", "c"]);
+        let ops = diff_lines(&old, &new);
+        assert!(matches!(ops[0], DiffOp::Equal(0, 0)));
+        assert!(matches!(ops[1], DiffOp::Delete(1)));
+        assert!(matches!(ops[2], DiffOp::Equal(2, 1)));
+    }
+
+    #[test]
+    fn diff_lines_replace() {
+        let old = lines(&["a", "b", "c"]);
+        let new = lines(&["a", "x", "c"]);
+        let ops = diff_lines(&old, &new);
+        assert!(matches!(ops[0], DiffOp::Equal(0, 0)));
+        assert!(matches!(ops[1], DiffOp::Delete(1)));
+        assert!(matches!(ops[2], DiffOp::Insert(1)));
+        assert!(matches!(ops[3], DiffOp::Equal(2, 2)));
+    }
+
+    #[test]
+    fn hunk_start_reports_the_first_op_in_range() {
+        let ops = vec![DiffOp::Equal(0, 0), DiffOp::Delete(1), DiffOp::Insert(1)];
+        assert_eq!(hunk_start(&ops, 1), (1, 1));
+        assert_eq!(hunk_start(&ops, 2), (1, 1));
+        assert_eq!(hunk_start(&ops, 0), (0, 0));
+    }
+
+    #[test]
+    fn hunk_start_past_the_end_defaults_to_origin() {
+        let ops = vec![DiffOp::Equal(0, 0)];
+        assert_eq!(hunk_start(&ops, 1), (0, 0));
+    }
+
+    #[test]
+    fn unified_diff_single_line_replace_has_one_hunk_with_correct_counts() {
+        let filename = FileName::Custom("test.rs".to_owned());
+        let diff = unified_diff(&filename, "a\nb\nc\n", "a\nx\nc\n", 1);
+        assert!(diff.contains("--- "));
+        assert!(diff.contains("+++ "));
+        assert_eq!(diff.matches("@@").count(), 2, "exactly one hunk header");
+        assert!(diff.contains("-b"));
+        assert!(diff.contains("+x"));
+        assert!(diff.contains(" a"));
+        assert!(diff.contains(" c"));
+    }
+
+    #[test]
+    fn unified_diff_far_apart_changes_produce_separate_hunks() {
+        let filename = FileName::Custom("test.rs".to_owned());
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "x\n2\n3\n4\n5\n6\n7\n8\n9\nx\n";
+        let diff = unified_diff(&filename, old, new, 1);
+        assert_eq!(
+            diff.matches("@@").count(),
+            4,
+            "two separate hunks for two far-apart changes"
+        );
+    }
 }
@@ -0,0 +1,86 @@
+//! Describes the concrete Rust type a pointer should be rewritten to. This is the data half of
+//! type rewriting: `rewrite::ty` turns a `TypeDesc` into a `rewrite::Rewrite` that builds the
+//! actual type syntax, and `rewrite::expr::mir_op` consults it to decide how to rewrite casts,
+//! dereferences, and offsets of the pointer it describes.
+
+use crate::rewrite::{LifetimeName, Rewrite};
+use rustc_hir::Mutability;
+
+/// The permission bits relevant to choosing a pointer's rewritten type. This mirrors the subset
+/// of the analysis's inferred `PermissionSet` that `perms_to_desc` needs; see the `null_ptr` test
+/// in `tests/filecheck/cast.rs` for the `READ | WRITE | UNIQUE` notation these come from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Perms(u8);
+
+impl Perms {
+    pub const READ: Perms = Perms(0b0001);
+    pub const WRITE: Perms = Perms(0b0010);
+    pub const UNIQUE: Perms = Perms(0b0100);
+    /// The pointer is known to never be null.
+    pub const NON_NULL: Perms = Perms(0b1000);
+
+    pub fn contains(self, other: Perms) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Perms {
+    type Output = Perms;
+    fn bitor(self, rhs: Perms) -> Perms {
+        Perms(self.0 | rhs.0)
+    }
+}
+
+/// The kind of pointer-like type a `TypeDesc` rewrites to.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PtrKind {
+    Ref(LifetimeName, Mutability),
+    Raw(Mutability),
+    Box,
+}
+
+/// The target type for rewriting a single pointer, as inferred from its permissions.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TypeDesc {
+    pub ptr: PtrKind,
+    pub pointee: Box<Rewrite>,
+    /// Set whenever the inferred permissions admit a null value for this pointer. When set, the
+    /// `ty` rewriter wraps the safe target type in `Option<...>`, and `mir_op` must rewrite casts,
+    /// dereferences, and offsets of this pointer through that `Option` rather than treating it as
+    /// infallibly non-null.
+    pub option: bool,
+}
+
+impl TypeDesc {
+    /// Build the `Rewrite::Ty*` tree for the target type itself, without the `Option<...>`
+    /// wrapper even when `self.option` is set. Used when the `Option` is already threaded
+    /// through by other means (e.g. a `.map(..)` closure) and only the inner type is needed.
+    pub fn base_rewrite(&self) -> Rewrite {
+        match &self.ptr {
+            PtrKind::Ref(lt, mutbl) => Rewrite::TyRef(lt.clone(), self.pointee.clone(), *mutbl),
+            PtrKind::Raw(mutbl) => Rewrite::TyPtr(self.pointee.clone(), *mutbl),
+            PtrKind::Box => Rewrite::TyCtor("Box".to_owned(), vec![(*self.pointee).clone()]),
+        }
+    }
+
+    /// Build the `Rewrite::Ty*` tree for this descriptor, wrapping it in `Option<...>` when
+    /// `self.option` is set.
+    pub fn to_rewrite(&self) -> Rewrite {
+        let base = self.base_rewrite();
+        if self.option {
+            Rewrite::TyCtor("Option".to_owned(), vec![base])
+        } else {
+            base
+        }
+    }
+}
+
+/// Translate inferred pointer permissions into a concrete rewrite target type. A pointer is
+/// nullable (`option = true`) exactly when the permissions don't guarantee `NON_NULL`.
+pub fn perms_to_desc(perms: Perms, ptr: PtrKind, pointee: Box<Rewrite>) -> TypeDesc {
+    TypeDesc {
+        ptr,
+        pointee,
+        option: !perms.contains(Perms::NON_NULL),
+    }
+}
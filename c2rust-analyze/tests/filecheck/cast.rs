@@ -12,9 +12,9 @@ struct S {
 pub unsafe fn null_ptr() {
     // CHECK-DAG: ([[@LINE+5]]: s): addr_of = UNIQUE, type = READ | WRITE | UNIQUE#
     // CHECK-LABEL: type assignment for "null_ptr":
-    // FIXME: regression: bad type rewrite; should be:
-    // XXXXX-DAG: ([[@LINE+1]]: s): &mut S
-    // CHECK-DAG: ([[@LINE+1]]: s): *mut S
+    // `s` lacks NON_NULL (it's only ever assigned from a `0 as *mut S` literal), so it's
+    // rewritten to the nullable `Option<&mut S>` rather than the unsound `&mut S`.
+    // CHECK-DAG: ([[@LINE+1]]: s): Option<&mut S>
     let s = 0 as *mut S;
     (*s).i = 0 as *const i32;
 }